@@ -0,0 +1,18 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlushError(pub String, pub usize, pub usize, pub String, pub Option<String>);
+
+pub type Result<T> = std::result::Result<T, FlushError>;
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.0, self.1, self.2, self.3)?;
+
+        if let Some(hint) = &self.4 {
+            write!(f, " ({hint})")?;
+        }
+
+        Ok(())
+    }
+}
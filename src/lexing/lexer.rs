@@ -1,58 +1,193 @@
-use super::typing::{Token, TokenKind};
+use unicode_xid::UnicodeXID;
+
+use super::typing::{Span, Token, TokenKind};
 use crate::error::{FlushError, Result};
 
+fn is_ident_start(character: char) -> bool {
+    character == '_' || character.is_xid_start()
+}
+
+fn is_ident_continue(character: char) -> bool {
+    character == '_' || character.is_xid_continue()
+}
+
+fn keyword_or_ident(identifier: String) -> TokenKind {
+    match identifier.as_str() {
+        "def" => TokenKind::Def,
+        "if" => TokenKind::If,
+        "else" => TokenKind::Else,
+        "while" => TokenKind::While,
+        "for" => TokenKind::For,
+        "in" => TokenKind::In,
+        "return" => TokenKind::Return,
+        "break" => TokenKind::Break,
+        "continue" => TokenKind::Continue,
+        "let" => TokenKind::Let,
+        "true" => TokenKind::True,
+        "false" => TokenKind::False,
+        _ => TokenKind::Ident(identifier),
+    }
+}
+
 #[derive(Default)]
 pub struct Lexer {
-    program: String,
+    chars: Vec<char>,
     file: String,
     tokens: Vec<Token>,
     position: usize,
     line: usize,
+    col: usize,
+    emitted_eof: bool,
 }
 
 impl Lexer {
     pub fn new(program: String, file: impl ToString) -> Self {
         Self {
-            program,
+            chars: program.chars().collect(),
             file: file.to_string(),
             line: 1,
+            col: 1,
             ..Default::default()
         }
     }
 
     fn previous(&self) -> Option<char> {
-        self.program.chars().nth(self.position - 1)
+        self.position.checked_sub(1).and_then(|i| self.chars.get(i)).copied()
     }
 
     fn current(&self) -> Option<char> {
-        self.program.chars().nth(self.position)
+        self.chars.get(self.position).copied()
     }
 
     fn advance(&mut self) -> Option<char> {
+        let character = self.current()?;
         self.position += 1;
-        self.previous()
+
+        if character == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+
+        Some(character)
     }
 
     fn is_at_end(&self) -> bool {
-        self.position >= self.program.len()
+        self.position >= self.chars.len()
+    }
+
+    fn here(&self) -> (usize, usize) {
+        (self.line, self.col)
     }
 
-    fn push_token(&mut self, token: TokenKind) {
+    fn push_token(&mut self, token: TokenKind, start: (usize, usize)) {
         self.tokens.push(Token {
-            line: self.line,
             kind: token,
+            span: Span {
+                start_line: start.0,
+                start_col: start.1,
+                end_line: self.line,
+                end_col: self.col,
+            },
         });
     }
 
     fn skip_comment(&mut self) {
-        while !self.is_at_end() && self.advance() != Some('\n') {
+        while !self.is_at_end() && self.current() != Some('\n') {
             self.advance();
         }
+    }
+
+    fn parse_escape(&mut self) -> Result<char> {
+        let start = self.here();
+        self.advance(); // skip \
+
+        let escaped = match self.advance() {
+            Some(character) => character,
+            None => {
+                return Err(FlushError(
+                    self.file.clone(),
+                    start.0,
+                    start.1,
+                    "Unterminated escape sequence".to_string(),
+                    None,
+                ))
+            }
+        };
 
-        self.line += 1;
+        match escaped {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            '0' => Ok('\0'),
+            'u' => self.parse_unicode_escape(start),
+            other => Err(FlushError(
+                self.file.clone(),
+                start.0,
+                start.1,
+                format!("Unknown escape sequence '\\{other}'"),
+                None,
+            )),
+        }
     }
 
-    fn parse_string(&mut self) -> Result<()> {
+    fn parse_unicode_escape(&mut self, start: (usize, usize)) -> Result<char> {
+        if self.current() != Some('{') {
+            return Err(FlushError(
+                self.file.clone(),
+                start.0,
+                start.1,
+                "Expected '{' after \\u".to_string(),
+                Some("use \\u{XXXX} to escape a unicode scalar".to_string()),
+            ));
+        }
+
+        self.advance(); // skip {
+
+        let mut hex = String::new();
+        while self.current().is_some_and(|c| c.is_ascii_hexdigit()) {
+            hex.push(self.advance().unwrap());
+        }
+
+        if hex.is_empty() || hex.len() > 6 {
+            return Err(FlushError(
+                self.file.clone(),
+                start.0,
+                start.1,
+                "Unicode escape must have between 1 and 6 hex digits".to_string(),
+                None,
+            ));
+        }
+
+        if self.current() != Some('}') {
+            return Err(FlushError(
+                self.file.clone(),
+                start.0,
+                start.1,
+                "Unterminated \\u{...} escape".to_string(),
+                None,
+            ));
+        }
+
+        self.advance(); // skip }
+
+        let code = u32::from_str_radix(&hex, 16).unwrap();
+
+        char::from_u32(code).ok_or_else(|| {
+            FlushError(
+                self.file.clone(),
+                start.0,
+                start.1,
+                format!("'\\u{{{hex}}}' is not a valid unicode scalar value"),
+                Some("surrogate halves and values above 10FFFF aren't valid".to_string()),
+            )
+        })
+    }
+
+    fn parse_string(&mut self, start: (usize, usize)) -> Result<()> {
         let mut string = String::new();
 
         while !self.is_at_end() && self.current() != Some('"') {
@@ -61,10 +196,15 @@ impl Lexer {
                     return Err(FlushError(
                         self.file.clone(),
                         self.line,
+                        self.col,
                         "Ilegal newline in string".to_string(),
                         Some("use \\n instead".to_string()),
                     ))
                 }
+                Some('\\') => {
+                    string.push(self.parse_escape()?);
+                    continue;
+                }
                 Some(character) => string.push(character),
                 None => break,
             };
@@ -76,18 +216,19 @@ impl Lexer {
             return Err(FlushError(
                 self.file.clone(),
                 self.line,
+                self.col,
                 "Unterminated string".to_string(),
                 None,
             ));
         }
 
         self.advance(); // skip "
-        self.push_token(TokenKind::String(string));
+        self.push_token(TokenKind::String(string), start);
 
         Ok(())
     }
 
-    fn parse_number(&mut self) {
+    fn parse_number(&mut self, start: (usize, usize)) {
         let mut raw_number = String::from(self.previous().unwrap());
 
         while !self.is_at_end() {
@@ -105,12 +246,14 @@ impl Lexer {
         }
 
         match raw_number.parse::<u32>() {
-            Ok(int) => self.push_token(TokenKind::Int(int)),
-            Err(_) => self.push_token(TokenKind::Float(raw_number.parse::<f32>().unwrap())),
+            Ok(int) => self.push_token(TokenKind::Int(int), start),
+            Err(_) => {
+                self.push_token(TokenKind::Float(raw_number.parse::<f32>().unwrap()), start)
+            }
         }
     }
 
-    fn parse_identifier(&mut self) {
+    fn parse_identifier(&mut self, start: (usize, usize)) {
         let mut identifier = String::from(self.previous().unwrap());
 
         while !self.is_at_end() {
@@ -119,7 +262,7 @@ impl Lexer {
                 None => break,
             };
 
-            if current.is_ascii_alphanumeric() {
+            if is_ident_continue(current) {
                 identifier.push(current);
                 self.advance();
             } else {
@@ -127,51 +270,199 @@ impl Lexer {
             }
         }
 
-        let token = match identifier.as_str() {
-            "def" => TokenKind::Def,
-            ident => TokenKind::Ident(ident.to_string()),
-        };
-
-        self.push_token(token);
+        self.push_token(keyword_or_ident(identifier), start);
     }
 
     fn parse_token(&mut self) -> Result<()> {
+        let start = self.here();
+
         let character = match self.advance() {
             Some(token) => token,
             None => return Ok(()),
         };
 
         match character {
-            '(' => self.push_token(TokenKind::LParen),
-            ')' => self.push_token(TokenKind::RParen),
-            '{' => self.push_token(TokenKind::LBrace),
-            '}' => self.push_token(TokenKind::RBrace),
-            '[' => self.push_token(TokenKind::LBracket),
-            ']' => self.push_token(TokenKind::RBracket),
-            ':' => self.push_token(TokenKind::Colon),
-            ';' => self.push_token(TokenKind::Semicolon),
-            ',' => self.push_token(TokenKind::Comma),
-            '+' | '-' | '*' | '/' | '%' | '^' | '=' => {
-                self.push_token(TokenKind::Operator(character))
+            '(' => self.push_token(TokenKind::LParen, start),
+            ')' => self.push_token(TokenKind::RParen, start),
+            '{' => self.push_token(TokenKind::LBrace, start),
+            '}' => self.push_token(TokenKind::RBrace, start),
+            '[' => self.push_token(TokenKind::LBracket, start),
+            ']' => self.push_token(TokenKind::RBracket, start),
+            ':' => self.push_token(TokenKind::Colon, start),
+            ';' => self.push_token(TokenKind::Semicolon, start),
+            ',' => self.push_token(TokenKind::Comma, start),
+            '+' | '-' | '*' | '/' | '%' | '^' | '=' | '<' | '>' | '!' | '&' | '|' => {
+                self.parse_operator(character, start)
             }
-            '"' => self.parse_string()?,
+            '"' => self.parse_string(start)?,
             '#' => self.skip_comment(),
-            '\n' => self.line += 1,
-            _ if character.is_ascii_digit() => self.parse_number(),
-            _ if character.is_ascii_alphanumeric() => self.parse_identifier(),
+            '\n' => (),
+            _ if character.is_ascii_digit() => self.parse_number(start),
+            _ if is_ident_start(character) => self.parse_identifier(start),
             _ => (),
         };
 
         Ok(())
     }
 
+    fn parse_operator(&mut self, character: char, start: (usize, usize)) {
+        let kind = match (character, self.current()) {
+            ('=', Some('=')) => {
+                self.advance();
+                TokenKind::EqEq
+            }
+            ('!', Some('=')) => {
+                self.advance();
+                TokenKind::BangEq
+            }
+            ('<', Some('=')) => {
+                self.advance();
+                TokenKind::Le
+            }
+            ('>', Some('=')) => {
+                self.advance();
+                TokenKind::Ge
+            }
+            ('&', Some('&')) => {
+                self.advance();
+                TokenKind::AndAnd
+            }
+            ('|', Some('|')) => {
+                self.advance();
+                TokenKind::OrOr
+            }
+            ('-', Some('>')) => {
+                self.advance();
+                TokenKind::Arrow
+            }
+            ('+', Some('=')) => {
+                self.advance();
+                TokenKind::PlusEq
+            }
+            ('-', Some('=')) => {
+                self.advance();
+                TokenKind::MinusEq
+            }
+            ('*', Some('=')) => {
+                self.advance();
+                TokenKind::StarEq
+            }
+            ('/', Some('=')) => {
+                self.advance();
+                TokenKind::SlashEq
+            }
+            ('%', Some('=')) => {
+                self.advance();
+                TokenKind::PercentEq
+            }
+            ('^', Some('=')) => {
+                self.advance();
+                TokenKind::CaretEq
+            }
+            ('+', _) => TokenKind::Plus,
+            ('-', _) => TokenKind::Minus,
+            ('*', _) => TokenKind::Star,
+            ('/', _) => TokenKind::Slash,
+            ('%', _) => TokenKind::Percent,
+            ('^', _) => TokenKind::Caret,
+            ('=', _) => TokenKind::Eq,
+            ('<', _) => TokenKind::Lt,
+            ('>', _) => TokenKind::Gt,
+            ('!', _) => TokenKind::Bang,
+            ('&', _) => TokenKind::Amp,
+            ('|', _) => TokenKind::Pipe,
+            (other, _) => unreachable!("parse_operator called with non-operator character '{other}'"),
+        };
+
+        self.push_token(kind, start);
+    }
+
+    fn skip_to_resync_point(&mut self) {
+        while !self.is_at_end() && self.current() != Some('\n') && self.current() != Some('"') {
+            self.advance();
+        }
+
+        if self.current() == Some('"') {
+            self.advance(); // skip past the closing quote
+        }
+    }
+
+    fn resync(&mut self, start: (usize, usize)) {
+        self.skip_to_resync_point();
+        self.push_token(TokenKind::Error, start);
+    }
+
+    /// Pulls a single token, parsing just enough input to produce it. Returns
+    /// `TokenKind::Eof` once (and forever after) the input is exhausted. On a
+    /// lex error the cursor is resynced past the offending input before
+    /// returning, so the next call picks back up at a sane boundary instead
+    /// of reinterpreting whatever the erroring scan left behind.
+    pub fn next_token(&mut self) -> Result<Token> {
+        loop {
+            if self.is_at_end() {
+                let here = self.here();
+                self.push_token(TokenKind::Eof, here);
+                return Ok(self.tokens.last().cloned().unwrap());
+            }
+
+            let before = self.tokens.len();
+
+            if let Err(error) = self.parse_token() {
+                self.skip_to_resync_point();
+                return Err(error);
+            }
+
+            if self.tokens.len() > before {
+                return Ok(self.tokens.last().cloned().unwrap());
+            }
+        }
+    }
+
     pub fn tokenize(&mut self) -> Result<Vec<Token>> {
-        while !self.is_at_end() {
-            self.parse_token()?
+        loop {
+            if self.next_token()?.kind == TokenKind::Eof {
+                break;
+            }
         }
 
         Ok(self.tokens.clone())
     }
+
+    pub fn tokenize_collect(&mut self) -> (Vec<Token>, Vec<FlushError>) {
+        let mut errors = Vec::new();
+
+        while !self.is_at_end() {
+            let start = self.here();
+
+            if let Err(error) = self.parse_token() {
+                errors.push(error);
+                self.resync(start);
+            }
+        }
+
+        let here = self.here();
+        self.push_token(TokenKind::Eof, here);
+
+        (self.tokens.clone(), errors)
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.emitted_eof {
+            return None;
+        }
+
+        let token = self.next_token();
+
+        if matches!(token, Ok(ref t) if t.kind == TokenKind::Eof) {
+            self.emitted_eof = true;
+        }
+
+        Some(token)
+    }
 }
 
 #[cfg(test)]
@@ -181,7 +472,11 @@ mod test {
     use crate::lexing::typing::{Token, TokenKind};
 
     fn get_types(tokens: Vec<Token>) -> Vec<TokenKind> {
-        tokens.into_iter().map(|t| t.kind.clone()).collect()
+        tokens
+            .into_iter()
+            .map(|t| t.kind)
+            .filter(|kind| *kind != TokenKind::Eof)
+            .collect()
     }
 
     #[test]
@@ -232,12 +527,32 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn string_escapes() -> Result<()> {
+        let mut lexer = Lexer::new(r#""a\nb\tc\"d\\e\u{1F600}""#.to_string(), "__test__");
+        assert_eq!(
+            get_types(lexer.tokenize()?),
+            vec![TokenKind::String("a\nb\tc\"d\\e\u{1F600}".to_string())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_escape() {
+        let mut lexer = Lexer::new(r#""\q""#.to_string(), "__test__");
+        match lexer.tokenize() {
+            Ok(_) => panic!(),
+            Err(e) => assert_eq!(e.3, "Unknown escape sequence '\\q'"),
+        }
+    }
+
     #[test]
     fn unclosed_string() {
         let mut lexer = Lexer::new(r#""Hello flush"#.to_string(), "__test__");
         match lexer.tokenize() {
             Ok(_) => panic!(),
-            Err(e) => assert_eq!(e.2, "Unterminated string"),
+            Err(e) => assert_eq!(e.3, "Unterminated string"),
         }
     }
 
@@ -254,10 +569,41 @@ mod test {
 
     #[test]
     fn keywords() -> Result<()> {
-        let mut lexer = Lexer::new("def user".to_string(), "__test__");
+        let mut lexer = Lexer::new(
+            "def user if else while for in return break continue let true false".to_string(),
+            "__test__",
+        );
+        assert_eq!(
+            get_types(lexer.tokenize()?),
+            vec![
+                TokenKind::Def,
+                TokenKind::Ident("user".to_string()),
+                TokenKind::If,
+                TokenKind::Else,
+                TokenKind::While,
+                TokenKind::For,
+                TokenKind::In,
+                TokenKind::Return,
+                TokenKind::Break,
+                TokenKind::Continue,
+                TokenKind::Let,
+                TokenKind::True,
+                TokenKind::False,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn identifiers_with_underscores_and_unicode() -> Result<()> {
+        let mut lexer = Lexer::new("_private café_au_lait".to_string(), "__test__");
         assert_eq!(
             get_types(lexer.tokenize()?),
-            vec![TokenKind::Def, TokenKind::Ident("user".to_string())]
+            vec![
+                TokenKind::Ident("_private".to_string()),
+                TokenKind::Ident("café_au_lait".to_string()),
+            ]
         );
 
         Ok(())
@@ -269,15 +615,131 @@ mod test {
         assert_eq!(
             get_types(lexer.tokenize()?),
             vec![
-                TokenKind::Operator('+'),
-                TokenKind::Operator('/'),
-                TokenKind::Operator('*'),
-                TokenKind::Operator('-'),
-                TokenKind::Operator('='),
-                TokenKind::Operator('%'),
+                TokenKind::Plus,
+                TokenKind::Slash,
+                TokenKind::Star,
+                TokenKind::Minus,
+                TokenKind::Eq,
+                TokenKind::Percent,
             ]
         );
 
         Ok(())
     }
+
+    #[test]
+    fn compound_operators() -> Result<()> {
+        let mut lexer = Lexer::new(
+            "== != <= >= && || -> += -= *= /= %= ^= < > ! & |".to_string(),
+            "__test__",
+        );
+        assert_eq!(
+            get_types(lexer.tokenize()?),
+            vec![
+                TokenKind::EqEq,
+                TokenKind::BangEq,
+                TokenKind::Le,
+                TokenKind::Ge,
+                TokenKind::AndAnd,
+                TokenKind::OrOr,
+                TokenKind::Arrow,
+                TokenKind::PlusEq,
+                TokenKind::MinusEq,
+                TokenKind::StarEq,
+                TokenKind::SlashEq,
+                TokenKind::PercentEq,
+                TokenKind::CaretEq,
+                TokenKind::Lt,
+                TokenKind::Gt,
+                TokenKind::Bang,
+                TokenKind::Amp,
+                TokenKind::Pipe,
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn tokenize_collect_gathers_every_error() {
+        let mut lexer = Lexer::new("\"unclosed\n32 \"\\q\" 18".to_string(), "__test__");
+        let (tokens, errors) = lexer.tokenize_collect();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            get_types(tokens),
+            vec![
+                TokenKind::Error,
+                TokenKind::Int(32),
+                TokenKind::Error,
+                TokenKind::Int(18),
+            ]
+        );
+    }
+
+    #[test]
+    fn tracks_spans_across_lines() -> Result<()> {
+        let mut lexer = Lexer::new("foo\nbar".to_string(), "__test__");
+        let tokens = lexer.tokenize()?;
+
+        assert_eq!(tokens[0].span.start_line, 1);
+        assert_eq!(tokens[0].span.start_col, 1);
+        assert_eq!(tokens[1].span.start_line, 2);
+        assert_eq!(tokens[1].span.start_col, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tokenize_ends_with_eof() -> Result<()> {
+        let mut lexer = Lexer::new("32".to_string(), "__test__");
+        let tokens = lexer.tokenize()?;
+
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+
+        Ok(())
+    }
+
+    #[test]
+    fn lexer_iterates_tokens_and_stops_after_eof() {
+        let lexer = Lexer::new("32 64".to_string(), "__test__");
+        let tokens: Vec<Token> = lexer.map(|t| t.unwrap()).collect();
+
+        assert_eq!(
+            get_types(tokens.clone()),
+            vec![TokenKind::Int(32), TokenKind::Int(64)]
+        );
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn tokenize_collect_ends_with_eof() {
+        let mut lexer = Lexer::new("32".to_string(), "__test__");
+        let (tokens, errors) = lexer.tokenize_collect();
+
+        assert!(errors.is_empty());
+        assert_eq!(tokens.last().unwrap().kind, TokenKind::Eof);
+    }
+
+    #[test]
+    fn next_token_resyncs_after_an_error_instead_of_reinterpreting_input() {
+        let lexer = Lexer::new(r#""\q" "trailing" 42"#.to_string(), "__test__");
+        let results: Vec<_> = lexer.collect();
+
+        assert!(results[0].is_err());
+
+        let kinds: Vec<TokenKind> = results
+            .into_iter()
+            .filter_map(|r| r.ok())
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::String("trailing".to_string()),
+                TokenKind::Int(42),
+                TokenKind::Eof,
+            ]
+        );
+    }
 }
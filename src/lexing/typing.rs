@@ -0,0 +1,73 @@
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    String(String),
+    Int(u32),
+    Float(f32),
+    Def,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    Return,
+    Break,
+    Continue,
+    Let,
+    True,
+    False,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Eq,
+    Lt,
+    Gt,
+    Bang,
+    Amp,
+    Pipe,
+    EqEq,
+    BangEq,
+    Le,
+    Ge,
+    AndAnd,
+    OrOr,
+    Arrow,
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    PercentEq,
+    CaretEq,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Colon,
+    Semicolon,
+    Comma,
+    /// Placeholder emitted by [`crate::lexing::lexer::Lexer::tokenize_collect`] at the point an
+    /// error was recovered from, so downstream consumers can see where a token was dropped.
+    Error,
+    /// Terminal marker emitted once at the end of input, so consumers don't have to
+    /// bounds-check the token stream.
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}